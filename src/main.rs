@@ -3,16 +3,25 @@
 use itertools::Itertools;
 
 use ansi_term::Color::{Blue, Green, Red};
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder};
+use clap::Parser;
 use futures::future::join_all;
+use futures::StreamExt;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Deserialize;
 use sha2::{Digest, Sha256};
-use shellexpand;
+use std::collections::HashMap;
 use std::io::BufRead;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
 use thiserror::Error;
 use tokio::fs::File;
-use tokio::io::ErrorKind;
-use tokio::prelude::*;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ErrorKind, ReadBuf};
+use tokio_util::io::StreamReader;
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 enum Error {
     #[error("Can't get data from url")]
     RequestError(#[from] reqwest::Error),
@@ -22,6 +31,25 @@ enum Error {
 
     #[error("Can't open file")]
     FileError(#[from] tokio::io::Error),
+
+    #[error("Can't parse manifest: {0}")]
+    ManifestParseError(String),
+
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+
+    #[error("Archive entry path escapes destination: {0}")]
+    PathTraversal(String),
+
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+
+    #[error("Environment variable `{0}` is not set")]
+    MissingTokenEnv(String),
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
 #[derive(Debug, PartialEq)]
@@ -31,86 +59,675 @@ enum State {
     Same,
 }
 
-fn hash_eq(buf1: &[u8], buf2: &[u8]) -> bool {
+async fn hash_file(path: &str) -> Result<Option<[u8; 32]>, Error> {
+    let mut file = match File::open(path).await {
+        Ok(f) => f,
+        Err(e) => {
+            if e.kind() == ErrorKind::NotFound {
+                return Ok(None);
+            }
+            return Err(Error::FileError(e));
+        }
+    };
+
     let mut hasher = Sha256::new();
-    hasher.input(buf1);
-    let buf1_hash = hasher.result_reset();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&hasher.finalize());
+    Ok(Some(digest))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RetryConfig {
+    max_retries: u32,
+    base_delay: Duration,
+}
+
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// `fetch_to_tmp` wraps the streaming body's `reqwest::Error`s in a generic
+/// `io::Error` so it can flow through an `AsyncRead` impl; this recovers the
+/// original error's network origin so a dropped connection is still treated
+/// as retryable instead of as an opaque file error.
+fn is_network_io_error(err: &std::io::Error) -> bool {
+    err.get_ref()
+        .map(|inner| inner.is::<reqwest::Error>())
+        .unwrap_or(false)
+}
+
+fn build_header_map(
+    global_headers: &[(String, String)],
+    task_headers: &[(String, String)],
+    token_env: Option<&str>,
+) -> Result<reqwest::header::HeaderMap, Error> {
+    let mut map = reqwest::header::HeaderMap::new();
+    for (key, value) in global_headers.iter().chain(task_headers.iter()) {
+        let name = reqwest::header::HeaderName::from_bytes(key.as_bytes())
+            .map_err(|e| Error::InvalidHeader(e.to_string()))?;
+        let value = reqwest::header::HeaderValue::from_str(value)
+            .map_err(|e| Error::InvalidHeader(e.to_string()))?;
+        map.insert(name, value);
+    }
+
+    if let Some(env_var) = token_env {
+        let token =
+            std::env::var(env_var).map_err(|_| Error::MissingTokenEnv(env_var.to_string()))?;
+        let value = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| Error::InvalidHeader(e.to_string()))?;
+        map.insert(reqwest::header::AUTHORIZATION, value);
+    }
 
-    hasher.input(buf2);
-    let buf2_hash = hasher.result_reset();
+    Ok(map)
+}
 
-    buf1_hash == buf2_hash
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum DecompressMode {
+    Auto,
+    Off,
 }
 
-async fn process(dt: &DownloadTask) -> Result<State, Error> {
-    let response = reqwest::get(&dt.remote_url).await?;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Codec {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+}
+
+fn detect_codec(remote_url: &str, content_encoding: Option<&str>) -> Codec {
+    match content_encoding {
+        Some("gzip") => return Codec::Gzip,
+        Some("bzip2") => return Codec::Bzip2,
+        Some("xz") => return Codec::Xz,
+        _ => {}
+    }
+
+    if remote_url.ends_with(".gz") || remote_url.ends_with(".tgz") {
+        Codec::Gzip
+    } else if remote_url.ends_with(".bz2") || remote_url.ends_with(".tbz2") {
+        Codec::Bzip2
+    } else if remote_url.ends_with(".xz") || remote_url.ends_with(".txz") {
+        Codec::Xz
+    } else {
+        Codec::None
+    }
+}
+
+fn byte_progress_style() -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template("{prefix:.bold} {bar:30.cyan/blue} {bytes}/{total_bytes} {msg}")
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+}
+
+fn spinner_progress_style() -> ProgressStyle {
+    ProgressStyle::default_spinner()
+        .template("{prefix:.bold} {spinner} {bytes} {msg}")
+        .unwrap_or_else(|_| ProgressStyle::default_spinner())
+}
+
+struct ProgressReader<R> {
+    inner: R,
+    bar: ProgressBar,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if poll.is_ready() {
+            let n = buf.filled().len() - before;
+            self.bar.inc(n as u64);
+        }
+        poll
+    }
+}
+
+async fn fetch_to_tmp(
+    client: &reqwest::Client,
+    remote_url: &str,
+    headers: &reqwest::header::HeaderMap,
+    tmp_path: &str,
+    decompress: DecompressMode,
+    bar: &ProgressBar,
+) -> Result<[u8; 32], Error> {
+    let response = client
+        .get(remote_url)
+        .headers(headers.clone())
+        .send()
+        .await?;
     if !response.status().is_success() {
         return Err(Error::WithStatusError(response.status()));
     }
 
-    let bytes_remote = response.bytes().await?;
+    bar.set_position(0);
+    match response.content_length() {
+        Some(len) => {
+            bar.set_length(len);
+            bar.set_style(byte_progress_style());
+        }
+        None => bar.set_style(spinner_progress_style()),
+    }
+
+    let codec = if decompress == DecompressMode::Off {
+        Codec::None
+    } else {
+        let content_encoding = response
+            .headers()
+            .get(reqwest::header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok());
+        detect_codec(remote_url, content_encoding)
+    };
+
+    let byte_stream = response
+        .bytes_stream()
+        .map(|r| r.map_err(std::io::Error::other));
+    let raw_reader = ProgressReader {
+        inner: tokio::io::BufReader::new(StreamReader::new(byte_stream)),
+        bar: bar.clone(),
+    };
+    let body_reader = tokio::io::BufReader::new(raw_reader);
+
+    let mut reader: Pin<Box<dyn AsyncRead + Send>> = match codec {
+        Codec::None => Box::pin(body_reader),
+        Codec::Gzip => Box::pin(GzipDecoder::new(body_reader)),
+        Codec::Bzip2 => Box::pin(BzDecoder::new(body_reader)),
+        Codec::Xz => Box::pin(XzDecoder::new(body_reader)),
+    };
+
+    let tmp_file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(tmp_path)
+        .await?;
+    let mut writer = tokio::io::BufWriter::new(tmp_file);
+
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        writer.write_all(&buf[..n]).await?;
+    }
+    writer.flush().await?;
+
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&hasher.finalize());
+    Ok(digest)
+}
 
-    let mut state: State = State::Update;
+async fn fetch_with_retry(
+    client: &reqwest::Client,
+    remote_url: &str,
+    headers: &reqwest::header::HeaderMap,
+    tmp_path: &str,
+    retry: &RetryConfig,
+    decompress: DecompressMode,
+    bar: &ProgressBar,
+) -> Result<[u8; 32], Error> {
+    let mut delay = retry.base_delay;
+    let mut attempt = 0;
+    loop {
+        let retryable = match fetch_to_tmp(client, remote_url, headers, tmp_path, decompress, bar)
+            .await
+        {
+            Ok(digest) => return Ok(digest),
+            Err(Error::RequestError(e)) if e.is_request() || e.is_timeout() => {
+                Some(Error::RequestError(e))
+            }
+            Err(Error::WithStatusError(status)) if is_retryable_status(status) => {
+                Some(Error::WithStatusError(status))
+            }
+            Err(Error::FileError(e)) if is_network_io_error(&e) => Some(Error::FileError(e)),
+            Err(e) => return Err(e),
+        };
+
+        if attempt >= retry.max_retries {
+            return Err(retryable.unwrap());
+        }
+        attempt += 1;
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+#[derive(Debug)]
+struct GlobalOpts {
+    retry: RetryConfig,
+    decompress: DecompressMode,
+    client: reqwest::Client,
+    headers: Vec<(String, String)>,
+    token_env: Option<String>,
+}
+
+async fn process_file(
+    dt: &DownloadTask,
+    opts: &GlobalOpts,
+    bar: &ProgressBar,
+) -> Result<State, Error> {
     let local_path = shellexpand::full(&dt.local_path).unwrap().into_owned();
+    let tmp_path = format!("{}.part", local_path);
+    let decompress = dt.decompress.unwrap_or(opts.decompress);
+    let token_env = dt.token_env.as_deref().or(opts.token_env.as_deref());
+    let headers = build_header_map(&opts.headers, &dt.headers, token_env)?;
 
-    let mut bytes_local = Vec::new();
-    let mut file = match File::open(&dt.local_path).await {
-        Ok(f) => f,
+    let remote_digest = fetch_with_retry(
+        &opts.client,
+        &dt.remote_url,
+        &headers,
+        &tmp_path,
+        &opts.retry,
+        decompress,
+        bar,
+    )
+    .await?;
+
+    if let Some(expected) = &dt.expected_sha256 {
+        let actual = to_hex(&remote_digest);
+        if expected.to_lowercase() != actual {
+            tokio::fs::remove_file(&tmp_path).await?;
+            return Err(Error::ChecksumMismatch {
+                expected: expected.clone(),
+                actual,
+            });
+        }
+    }
+
+    let local_digest = hash_file(&local_path).await?;
+    let state = match local_digest {
+        None => State::New,
+        Some(digest) if digest == remote_digest => State::Same,
+        Some(_) => State::Update,
+    };
+
+    if state == State::Same {
+        tokio::fs::remove_file(&tmp_path).await?;
+    } else {
+        tokio::fs::rename(&tmp_path, &local_path).await?;
+    }
+    Ok(state)
+}
+
+fn tar_archive_decoder(
+    reader: tokio::io::BufReader<File>,
+    codec: Codec,
+) -> Pin<Box<dyn AsyncRead + Send>> {
+    match codec {
+        Codec::None => Box::pin(reader),
+        Codec::Gzip => Box::pin(GzipDecoder::new(reader)),
+        Codec::Bzip2 => Box::pin(BzDecoder::new(reader)),
+        Codec::Xz => Box::pin(XzDecoder::new(reader)),
+    }
+}
+
+/// Resolves a path taken from a tar entry (its own name, or a symlink/hardlink
+/// target) against `dest`, rejecting anything that would textually land
+/// outside it (absolute paths or `..` components). This only checks the
+/// *declared* path — it does not know whether an already-extracted ancestor
+/// component is itself a symlink; see `reject_symlink_ancestors` for that.
+fn resolve_entry_path(
+    dest: &std::path::Path,
+    entry_path: &std::path::Path,
+) -> Result<std::path::PathBuf, Error> {
+    if entry_path.is_absolute()
+        || entry_path.components().any(|c| {
+            matches!(
+                c,
+                std::path::Component::ParentDir | std::path::Component::RootDir
+            )
+        })
+    {
+        return Err(Error::PathTraversal(entry_path.display().to_string()));
+    }
+
+    let target = dest.join(entry_path);
+    if !target.starts_with(dest) {
+        return Err(Error::PathTraversal(entry_path.display().to_string()));
+    }
+    Ok(target)
+}
+
+/// Guards against an earlier entry having extracted a symlink that a later
+/// entry's path then traverses through (e.g. `link -> /outside` followed by
+/// `link/evil.txt`): `resolve_entry_path` only looks at the textual path, so
+/// it can't catch this on its own. Walks every ancestor component of
+/// `entry_path` under `dest`, other than the entry itself, and rejects if any
+/// of them already exists on disk as a symlink.
+async fn reject_symlink_ancestors(
+    dest: &std::path::Path,
+    entry_path: &std::path::Path,
+) -> Result<(), Error> {
+    let mut current = dest.to_path_buf();
+    let mut components = entry_path.components().peekable();
+    while let Some(component) = components.next() {
+        current.push(component);
+        if components.peek().is_none() {
+            break;
+        }
+        if let Ok(meta) = tokio::fs::symlink_metadata(&current).await {
+            if meta.file_type().is_symlink() {
+                return Err(Error::PathTraversal(entry_path.display().to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn extract_tar(archive_path: &str, dest: &str, codec: Codec) -> Result<(), Error> {
+    tokio::fs::create_dir_all(dest).await?;
+    let dest = tokio::fs::canonicalize(dest).await?;
+
+    let file = File::open(archive_path).await?;
+    let reader = tokio::io::BufReader::new(file);
+    let decoded = tar_archive_decoder(reader, codec);
+
+    let mut archive = tokio_tar::Archive::new(decoded);
+    let mut entries = archive.entries()?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        let target = resolve_entry_path(&dest, &entry_path)?;
+        reject_symlink_ancestors(&dest, &entry_path).await?;
+
+        let entry_type = entry.header().entry_type();
+        if matches!(
+            entry_type,
+            tokio_tar::EntryType::Symlink | tokio_tar::EntryType::Link
+        ) {
+            if let Some(link_name) = entry.link_name()? {
+                resolve_entry_path(&dest, &link_name)?;
+            }
+        }
+
+        entry.unpack(&target).await?;
+    }
+
+    Ok(())
+}
+
+async fn process_tar(
+    dt: &DownloadTask,
+    opts: &GlobalOpts,
+    bar: &ProgressBar,
+) -> Result<State, Error> {
+    let dest = shellexpand::full(&dt.local_path).unwrap().into_owned();
+    let decompress = dt.decompress.unwrap_or(opts.decompress);
+    let token_env = dt.token_env.as_deref().or(opts.token_env.as_deref());
+    let headers = build_header_map(&opts.headers, &dt.headers, token_env)?;
+    let tmp_archive = format!("{}.tar.part", dest);
+    let marker_path = format!("{}.sha256", dest);
+
+    // Fetch the raw archive first so we can compare its digest against the
+    // marker left by a previous run before paying the cost of extraction.
+    let digest = fetch_with_retry(
+        &opts.client,
+        &dt.remote_url,
+        &headers,
+        &tmp_archive,
+        &opts.retry,
+        DecompressMode::Off,
+        bar,
+    )
+    .await?;
+    let digest_hex = to_hex(&digest);
+
+    if let Some(expected) = &dt.expected_sha256 {
+        if expected.to_lowercase() != digest_hex {
+            tokio::fs::remove_file(&tmp_archive).await?;
+            return Err(Error::ChecksumMismatch {
+                expected: expected.clone(),
+                actual: digest_hex,
+            });
+        }
+    }
+
+    let previous = match tokio::fs::read_to_string(&marker_path).await {
+        Ok(s) => Some(s.trim().to_lowercase()),
         Err(e) => {
             if e.kind() == ErrorKind::NotFound {
-                state = State::New;
-
-                // Create new file and read it
-                tokio::fs::OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .create(true)
-                    .open(&local_path)
-                    .await?
+                None
             } else {
                 return Err(Error::FileError(e));
             }
         }
     };
-    file.read_to_end(&mut bytes_local).await?;
 
-    if !hash_eq(&bytes_local, &bytes_remote) {
-        if state != State::New {
-            state = State::Update;
-        }
-        let mut file = File::create(&local_path).await?;
-        file.write_all(&bytes_remote).await?;
+    if previous.as_deref() == Some(digest_hex.as_str()) {
+        tokio::fs::remove_file(&tmp_archive).await?;
+        return Ok(State::Same);
+    }
+    let state = if previous.is_none() {
+        State::New
+    } else {
+        State::Update
+    };
+
+    let codec = if decompress == DecompressMode::Off {
+        Codec::None
     } else {
-        state = State::Same;
+        detect_codec(&dt.remote_url, None)
+    };
+
+    // Extract into a fresh directory and swap it into place rather than
+    // unpacking on top of `dest`, so members dropped from a newer archive
+    // don't linger from the previous extraction.
+    let tmp_dest = format!("{}.extract.part", dest);
+    if tokio::fs::try_exists(&tmp_dest).await? {
+        tokio::fs::remove_dir_all(&tmp_dest).await?;
     }
+    extract_tar(&tmp_archive, &tmp_dest, codec).await?;
+    tokio::fs::remove_file(&tmp_archive).await?;
+
+    if tokio::fs::try_exists(&dest).await? {
+        tokio::fs::remove_dir_all(&dest).await?;
+    }
+    tokio::fs::rename(&tmp_dest, &dest).await?;
+    tokio::fs::write(&marker_path, &digest_hex).await?;
+
     Ok(state)
 }
 
+async fn process(dt: &DownloadTask, opts: &GlobalOpts, bar: &ProgressBar) -> Result<State, Error> {
+    match dt.kind {
+        TaskKind::File => process_file(dt, opts, bar).await,
+        TaskKind::Tar => process_tar(dt, opts, bar).await,
+    }
+}
+
+fn status_message(local_path: &str, result: &Result<State, Error>) -> String {
+    match result {
+        Ok(State::Same) => local_path.to_string(),
+        Ok(State::Update) => format!("{}", Blue.bold().paint(local_path)),
+        Ok(State::New) => format!("{}", Green.bold().paint(local_path)),
+        Err(Error::WithStatusError(status)) => format!(
+            "{} {}",
+            Red.bold().paint(local_path),
+            Red.bold().paint(status.as_str())
+        ),
+        Err(err) => format!(
+            "{} {}",
+            Red.bold().paint(local_path),
+            Red.bold().paint(err.to_string())
+        ),
+    }
+}
+
+async fn process_with_progress(
+    dt: &DownloadTask,
+    opts: &GlobalOpts,
+    bar: &ProgressBar,
+) -> Result<State, Error> {
+    let result = process(dt, opts, bar).await;
+    bar.finish_with_message(status_message(&dt.local_path, &result));
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TaskKind {
+    File,
+    Tar,
+}
+
+const TAR_EXTENSIONS: [&str; 7] = [
+    ".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tar.xz", ".txz",
+];
+
+fn detect_task_kind(remote_url: &str) -> TaskKind {
+    if TAR_EXTENSIONS.iter().any(|ext| remote_url.ends_with(ext)) {
+        TaskKind::Tar
+    } else {
+        TaskKind::File
+    }
+}
+
 #[derive(Debug)]
 struct DownloadTask {
     remote_url: String,
     local_path: String,
+    expected_sha256: Option<String>,
+    decompress: Option<DecompressMode>,
+    kind: TaskKind,
+    headers: Vec<(String, String)>,
+    token_env: Option<String>,
 }
 
 impl DownloadTask {
     fn new(remote_url: String, local_path: String) -> Self {
+        let kind = detect_task_kind(&remote_url);
         Self {
             remote_url,
             local_path,
+            expected_sha256: None,
+            decompress: None,
+            kind,
+            headers: Vec::new(),
+            token_env: None,
         }
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct ManifestEntry {
+    remote_url: String,
+    local_path: String,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    decompress: Option<DecompressMode>,
+    #[serde(default)]
+    kind: Option<TaskKind>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    token_env: Option<String>,
+}
+
+/// TOML has no bare-sequence root, so entries must live under a named table
+/// (`[[task]]`); YAML is parsed the same way for consistency between the two
+/// formats.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    task: Vec<ManifestEntry>,
+}
+
+fn load_manifest(path: &str) -> Result<Vec<DownloadTask>, Error> {
+    let content = std::fs::read_to_string(path)?;
+    let manifest: Manifest = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&content).map_err(|e| Error::ManifestParseError(e.to_string()))?
+    } else {
+        toml::from_str(&content).map_err(|e| Error::ManifestParseError(e.to_string()))?
+    };
+
+    Ok(manifest
+        .task
+        .into_iter()
+        .map(|e| {
+            let kind = e.kind.unwrap_or_else(|| detect_task_kind(&e.remote_url));
+            DownloadTask {
+                remote_url: e.remote_url,
+                local_path: e.local_path,
+                expected_sha256: e.sha256,
+                decompress: e.decompress,
+                kind,
+                headers: e.headers.into_iter().collect(),
+                token_env: e.token_env,
+            }
+        })
+        .collect())
+}
+
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("invalid header `{}`, expected KEY:VALUE", s))?;
+    Ok((key.trim().to_string(), value.trim().to_string()))
+}
+
+/// Download or mirror remote files, verifying content by SHA256.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Path to a manifest file (TOML or YAML) listing download tasks,
+    /// in addition to any `remote_url`/`local_path` pairs read from stdin.
+    #[clap(long)]
+    manifest: Option<String>,
+
+    /// Maximum number of retry attempts for transient failures
+    #[clap(long, default_value = "3")]
+    retries: u32,
+
+    /// Initial backoff delay in milliseconds, doubled on each retry
+    #[clap(long, default_value = "1000")]
+    retry_base_delay: u64,
+
+    /// Transparently decompress gzip/bzip2/xz responses before writing
+    #[clap(long, value_enum, default_value = "auto")]
+    decompress: DecompressMode,
+
+    /// Extra HTTP header applied to every request, as KEY:VALUE. Repeatable.
+    #[clap(long = "header", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+
+    /// Name of an environment variable whose value is sent as `Authorization: Bearer <value>`
+    #[clap(long)]
+    token_env: Option<String>,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
+    let args = Args::parse();
+
     let mut files: Vec<DownloadTask> = Vec::new();
 
+    if let Some(manifest_path) = &args.manifest {
+        files.extend(load_manifest(manifest_path)?);
+    }
+
     let stdin = std::io::stdin();
     for (not_empty, mut line) in stdin
         .lock()
         .lines()
         .map(|l| l.unwrap())
-        .group_by(|el| *el != "")
+        .group_by(|el| !el.is_empty())
         .into_iter()
     {
         let remote_url = line.next().unwrap();
@@ -120,39 +737,142 @@ async fn main() -> Result<(), Error> {
         }
     }
 
-    let processes: Vec<_> = files.iter().map(|dt| process(dt)).collect();
-
-    let results = join_all(processes).await;
-
-    let width = files.iter().map(|f| f.local_path.len()).max().unwrap_or(0) + 2;
-    for (r, f) in results.iter().zip(files) {
-        let line = match r {
-            Ok(State::Same) => format!("{:<width$}", f.local_path, width = width),
-            Ok(State::Update) => format!(
-                "{}",
-                Blue.bold()
-                    .paint(format!("{:<width$}", f.local_path, width = width))
-            ),
-            Ok(State::New) => format!(
-                "{}",
-                Green
-                    .bold()
-                    .paint(format!("{:<width$}", f.local_path, width = width))
-            ),
-            Err(Error::WithStatusError(status)) => format!(
-                "{} {}",
-                Red.bold()
-                    .paint(format!("{:<width$}", f.local_path, width = width)),
-                Red.bold().paint(status.as_str())
-            ),
-            Err(err) => format!(
-                "{} {}",
-                Red.bold()
-                    .paint(format!("{:<width$}", f.local_path, width = width)),
-                Red.bold().paint(err.to_string())
-            ),
-        };
-        println!("{}", line);
-    }
+    let opts = GlobalOpts {
+        retry: RetryConfig {
+            max_retries: args.retries,
+            base_delay: Duration::from_millis(args.retry_base_delay),
+        },
+        decompress: args.decompress,
+        client: reqwest::Client::new(),
+        headers: args.headers,
+        token_env: args.token_env,
+    };
+
+    let multi = MultiProgress::new();
+    let bars: Vec<ProgressBar> = files
+        .iter()
+        .map(|f| {
+            let bar = multi.add(ProgressBar::new(0));
+            bar.set_style(spinner_progress_style());
+            bar.set_prefix(f.local_path.clone());
+            bar
+        })
+        .collect();
+
+    let processes: Vec<_> = files
+        .iter()
+        .zip(bars.iter())
+        .map(|(dt, bar)| process_with_progress(dt, &opts, bar))
+        .collect();
+
+    join_all(processes).await;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_tmp(suffix: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "raw-downloader-test-{}-{}{}",
+            std::process::id(),
+            content.len(),
+            suffix
+        ));
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn load_manifest_parses_toml_task_table() {
+        let path = write_tmp(
+            ".toml",
+            r#"
+            [[task]]
+            remote_url = "https://example.com/a.bin"
+            local_path = "/tmp/a.bin"
+            sha256 = "deadbeef"
+            "#,
+        );
+        let tasks = load_manifest(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].remote_url, "https://example.com/a.bin");
+        assert_eq!(tasks[0].expected_sha256.as_deref(), Some("deadbeef"));
+    }
+
+    #[test]
+    fn load_manifest_parses_yaml_task_list() {
+        let path = write_tmp(
+            ".yaml",
+            "task:\n  - remote_url: https://example.com/a.bin\n    local_path: /tmp/a.bin\n",
+        );
+        let tasks = load_manifest(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].local_path, "/tmp/a.bin");
+        assert!(tasks[0].expected_sha256.is_none());
+    }
+
+    #[test]
+    fn detect_codec_recognizes_short_tar_extensions() {
+        assert_eq!(detect_codec("https://x/a.tgz", None), Codec::Gzip);
+        assert_eq!(detect_codec("https://x/a.tbz2", None), Codec::Bzip2);
+        assert_eq!(detect_codec("https://x/a.txz", None), Codec::Xz);
+        assert_eq!(detect_codec("https://x/a.tar", None), Codec::None);
+    }
+
+    #[test]
+    fn resolve_entry_path_rejects_traversal_and_absolute() {
+        let dest = std::path::Path::new("/tmp/dest");
+        assert!(resolve_entry_path(dest, std::path::Path::new("../escape")).is_err());
+        assert!(resolve_entry_path(dest, std::path::Path::new("/etc/passwd")).is_err());
+        assert!(resolve_entry_path(dest, std::path::Path::new("nested/../../escape")).is_err());
+    }
+
+    #[test]
+    fn resolve_entry_path_accepts_nested_path() {
+        let dest = std::path::Path::new("/tmp/dest");
+        let target = resolve_entry_path(dest, std::path::Path::new("a/b.txt")).unwrap();
+        assert_eq!(target, dest.join("a/b.txt"));
+    }
+
+    #[tokio::test]
+    async fn reject_symlink_ancestors_catches_escape_through_existing_symlink() {
+        let root = std::env::temp_dir().join(format!(
+            "raw-downloader-symlink-test-{}",
+            std::process::id()
+        ));
+        let dest = root.join("dest");
+        let outside = root.join("outside");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&dest).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, dest.join("link")).unwrap();
+
+        let result = reject_symlink_ancestors(&dest, std::path::Path::new("link/evil.txt")).await;
+
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn reject_symlink_ancestors_allows_plain_nested_path() {
+        let root = std::env::temp_dir().join(format!(
+            "raw-downloader-symlink-ok-test-{}",
+            std::process::id()
+        ));
+        let dest = root.join("dest");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(dest.join("a")).unwrap();
+
+        let result = reject_symlink_ancestors(&dest, std::path::Path::new("a/b.txt")).await;
+
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(result.is_ok());
+    }
+}